@@ -0,0 +1,214 @@
+//! Merging independent offers (or arbitrary spend bundles) into one aggregate-signed bundle.
+//!
+//! Each input bundle is already internally valid, so combining them is a matter of taking the
+//! union of coin spends and adding the BLS signatures together. Before accepting the result,
+//! every coin spend's `AGG_SIG_ME`/`AGG_SIG_UNSAFE` conditions are collected and checked against
+//! the combined signature with `aggregate_verify`, so a bundle that doesn't actually carry a
+//! valid signature for its own spends can't ride along with an otherwise-valid offer.
+
+use std::collections::HashMap;
+
+use chia_bls::{PublicKey, aggregate, aggregate_verify};
+use chia_protocol::{Bytes32, CoinSpend, SpendBundle};
+use chia_sdk_driver::SpendContext;
+use chia_sdk_types::Condition;
+use pyo3::PyResult;
+use pyo3::exceptions::PyValueError;
+
+/// Mainnet's `AGG_SIG_ME_ADDITIONAL_DATA` (the genesis challenge), appended to every
+/// `AGG_SIG_ME` condition's message before it's checked against the aggregated signature.
+/// Combining offers built for a different network isn't supported.
+const AGG_SIG_ME_ADDITIONAL_DATA: [u8; 32] = [
+    0xcc, 0xd5, 0xbb, 0x71, 0x18, 0x35, 0x32, 0xbf, 0xf2, 0x20, 0xba, 0x46, 0xc2, 0x68, 0x99, 0x1a,
+    0x3f, 0xf0, 0x7e, 0xb3, 0x58, 0xe8, 0x25, 0x5a, 0x65, 0xc3, 0x0a, 0x2d, 0xce, 0x0e, 0x5f, 0xbb,
+];
+
+/// Every `(public_key, message)` pair a coin spend's `AGG_SIG_ME`/`AGG_SIG_UNSAFE` conditions
+/// demand a signature over, by running its puzzle against its solution.
+fn signed_messages(
+    ctx: &mut SpendContext,
+    coin_spend: &CoinSpend,
+) -> Result<Vec<(PublicKey, Vec<u8>)>, String> {
+    let puzzle = ctx
+        .alloc(&coin_spend.puzzle_reveal)
+        .map_err(|err| err.to_string())?;
+    let solution = ctx
+        .alloc(&coin_spend.solution)
+        .map_err(|err| err.to_string())?;
+    let output = ctx.run(puzzle, solution).map_err(|err| err.to_string())?;
+    let conditions =
+        Condition::parse_conditions(ctx.allocator(), output).map_err(|err| err.to_string())?;
+
+    let coin_id = coin_spend.coin.coin_id();
+    let mut pairs = Vec::new();
+    for condition in conditions {
+        match condition {
+            Condition::AggSigMe(agg_sig_me) => {
+                let mut message = agg_sig_me.message.to_vec();
+                message.extend_from_slice(&coin_id);
+                message.extend_from_slice(&AGG_SIG_ME_ADDITIONAL_DATA);
+                pairs.push((agg_sig_me.public_key, message));
+            }
+            Condition::AggSigUnsafe(agg_sig_unsafe) => {
+                pairs.push((agg_sig_unsafe.public_key, agg_sig_unsafe.message.to_vec()));
+            }
+            _ => {}
+        }
+    }
+    Ok(pairs)
+}
+
+pub fn combine_spend_bundles(spend_bundles: Vec<SpendBundle>) -> PyResult<SpendBundle> {
+    if spend_bundles.is_empty() {
+        return Err(PyValueError::new_err(
+            "combine_offers requires at least one offer",
+        ));
+    }
+
+    let mut ctx = SpendContext::new();
+    let mut coin_spends: Vec<CoinSpend> = Vec::new();
+    let mut seen: HashMap<Bytes32, usize> = HashMap::new();
+    let mut signatures = Vec::with_capacity(spend_bundles.len());
+    let mut signed_pairs: Vec<(PublicKey, Vec<u8>)> = Vec::new();
+
+    for spend_bundle in spend_bundles {
+        signatures.push(spend_bundle.aggregated_signature);
+        for coin_spend in spend_bundle.coin_spends {
+            let coin_id = coin_spend.coin.coin_id();
+            if let Some(&existing_index) = seen.get(&coin_id) {
+                if coin_spends[existing_index] != coin_spend {
+                    return Err(PyValueError::new_err(format!(
+                        "conflicting spends for coin {coin_id}"
+                    )));
+                }
+                continue;
+            }
+            signed_pairs.extend(signed_messages(&mut ctx, &coin_spend).map_err(PyValueError::new_err)?);
+            seen.insert(coin_id, coin_spends.len());
+            coin_spends.push(coin_spend);
+        }
+    }
+
+    let aggregated_signature = aggregate(&signatures);
+    let verifies = aggregate_verify(
+        &aggregated_signature,
+        signed_pairs.iter().map(|(pk, msg)| (pk, msg.as_slice())),
+    );
+    if !verifies {
+        return Err(PyValueError::new_err(
+            "combined signature does not verify against the combined spends",
+        ));
+    }
+
+    Ok(SpendBundle::new(coin_spends, aggregated_signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chia_bls::{SecretKey, Signature, sign};
+    use chia_protocol::{Coin, Program};
+    use chia_sdk_types::AggSigMe;
+    use clvm_traits::clvm_quote;
+
+    fn secret_key(seed: u8) -> SecretKey {
+        SecretKey::from_seed(&[seed; 32])
+    }
+
+    fn agg_sig_me_message(coin_id: Bytes32, message: &[u8]) -> Vec<u8> {
+        let mut full_message = message.to_vec();
+        full_message.extend_from_slice(&coin_id);
+        full_message.extend_from_slice(&AGG_SIG_ME_ADDITIONAL_DATA);
+        full_message
+    }
+
+    /// A coin spend whose puzzle ignores its solution and unconditionally returns `conditions`
+    /// (a quoted puzzle, `(q . conditions)`), so running it always yields exactly those conditions.
+    fn conditions_coin_spend(ctx: &mut SpendContext, coin: Coin, conditions: Vec<Condition>) -> CoinSpend {
+        let puzzle_reveal = ctx
+            .serialize(&clvm_quote!(conditions))
+            .expect("serialize conditions puzzle");
+        let solution = Program::from_bytes(&[0x80]).expect("nil solution");
+        CoinSpend::new(coin, puzzle_reveal, solution)
+    }
+
+    fn agg_sig_me_spend(
+        ctx: &mut SpendContext,
+        coin: Coin,
+        public_key: PublicKey,
+        message: &[u8],
+    ) -> CoinSpend {
+        let condition = Condition::AggSigMe(AggSigMe {
+            public_key,
+            message: message.to_vec().into(),
+        });
+        conditions_coin_spend(ctx, coin, vec![condition])
+    }
+
+    #[test]
+    fn rejects_a_combine_with_a_tampered_signature() {
+        let mut ctx = SpendContext::new();
+        let sk = secret_key(1);
+        let coin = Coin::new(Bytes32::new([1; 32]), Bytes32::new([2; 32]), 1_000);
+        let coin_spend = agg_sig_me_spend(&mut ctx, coin, sk.public_key(), b"hello");
+
+        // The correct signature is swapped out for an unrelated one, so it doesn't match the
+        // (public_key, message) pair the coin spend's AGG_SIG_ME condition demands.
+        let tampered_signature = sign(&secret_key(2), b"wrong message");
+        let spend_bundle = SpendBundle::new(vec![coin_spend], tampered_signature);
+
+        let err = combine_spend_bundles(vec![spend_bundle]).unwrap_err();
+        assert!(err.to_string().contains("does not verify"));
+    }
+
+    #[test]
+    fn rejects_a_combine_with_a_missing_signature() {
+        let mut ctx = SpendContext::new();
+        let sk = secret_key(1);
+        let coin = Coin::new(Bytes32::new([1; 32]), Bytes32::new([2; 32]), 1_000);
+        let coin_spend = agg_sig_me_spend(&mut ctx, coin, sk.public_key(), b"hello");
+
+        let spend_bundle = SpendBundle::new(vec![coin_spend], Signature::default());
+
+        let err = combine_spend_bundles(vec![spend_bundle]).unwrap_err();
+        assert!(err.to_string().contains("does not verify"));
+    }
+
+    #[test]
+    fn combines_offers_deduping_an_overlapping_coin_spend() {
+        let mut ctx = SpendContext::new();
+
+        let sk_a = secret_key(1);
+        let sk_shared = secret_key(2);
+        let sk_b = secret_key(3);
+
+        let coin_a = Coin::new(Bytes32::new([1; 32]), Bytes32::new([10; 32]), 1_000);
+        let coin_shared = Coin::new(Bytes32::new([2; 32]), Bytes32::new([20; 32]), 2_000);
+        let coin_b = Coin::new(Bytes32::new([3; 32]), Bytes32::new([30; 32]), 3_000);
+
+        let spend_a = agg_sig_me_spend(&mut ctx, coin_a, sk_a.public_key(), b"a");
+        let spend_shared = agg_sig_me_spend(&mut ctx, coin_shared, sk_shared.public_key(), b"shared");
+        let spend_b = agg_sig_me_spend(&mut ctx, coin_b, sk_b.public_key(), b"b");
+
+        let sig_a = sign(&sk_a, &agg_sig_me_message(coin_a.coin_id(), b"a"));
+        let sig_shared = sign(&sk_shared, &agg_sig_me_message(coin_shared.coin_id(), b"shared"));
+        let sig_b = sign(&sk_b, &agg_sig_me_message(coin_b.coin_id(), b"b"));
+
+        // Two independently-built offers both happen to spend `coin_shared` the same way. Only the
+        // first carries its signature; the second just repeats the identical spend, the way a
+        // wallet re-including an already-signed coin from another offer would. Combining must not
+        // double-count it: the combined signature only has to cover it once.
+        let offer_one = SpendBundle::new(
+            vec![spend_a, spend_shared.clone()],
+            aggregate([sig_a, sig_shared]),
+        );
+        let offer_two = SpendBundle::new(vec![spend_shared, spend_b], sig_b);
+
+        let combined = combine_spend_bundles(vec![offer_one, offer_two])
+            .expect("legitimate combine with an overlapping coin spend should succeed");
+
+        assert_eq!(combined.coin_spends.len(), 3);
+        assert_eq!(combined.aggregated_signature, aggregate([sig_a, sig_shared, sig_b]));
+    }
+}
@@ -0,0 +1,56 @@
+//! Conversion helpers from `chia_sdk_driver` offer types into native Python objects.
+
+use chia_protocol::Bytes32;
+use chia_puzzle_types::{Memos, offer::NotarizedPayment};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList};
+
+pub(crate) fn bytes32_to_py(py: Python<'_>, value: Bytes32) -> PyObject {
+    PyBytes::new(py, &value).into()
+}
+
+fn memos_to_py(py: Python<'_>, memos: &Memos) -> PyObject {
+    match memos {
+        Memos::None => py.None(),
+        Memos::Some(values) => PyList::new(
+            py,
+            values.iter().map(|memo| PyBytes::new(py, memo).into_any()),
+        )
+        .expect("memo list is always constructible")
+        .into(),
+    }
+}
+
+pub fn notarized_payment_to_py(py: Python<'_>, payment: &NotarizedPayment) -> PyResult<Py<PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("nonce", bytes32_to_py(py, payment.nonce))?;
+    let payments = PyList::empty(py);
+    for entry in &payment.payments {
+        let entry_dict = PyDict::new(py);
+        entry_dict.set_item("puzzle_hash", bytes32_to_py(py, entry.puzzle_hash))?;
+        entry_dict.set_item("amount", entry.amount)?;
+        entry_dict.set_item("memos", memos_to_py(py, &entry.memos))?;
+        payments.append(entry_dict)?;
+    }
+    dict.set_item("payments", payments)?;
+    Ok(dict.into())
+}
+
+/// `(asset_kind, asset_id, amount)` for a single offered coin, as classified by
+/// `classify_offered_coin`. `asset_kind` is `"xch"`, `"cat"`, or `"nft"`; `asset_id` is the CAT's
+/// asset id or the NFT's launcher id, and is `None` for `"xch"`.
+pub fn offered_asset_to_py(
+    py: Python<'_>,
+    asset_kind: &str,
+    asset_id: Option<Bytes32>,
+    amount: u64,
+) -> PyResult<Py<PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("asset_kind", asset_kind)?;
+    dict.set_item(
+        "asset_id",
+        asset_id.map_or_else(|| py.None(), |asset_id| bytes32_to_py(py, asset_id)),
+    )?;
+    dict.set_item("amount", amount)?;
+    Ok(dict.into())
+}
@@ -0,0 +1,53 @@
+//! Fast-forwarding singleton spends inside an offer: when an offer's singleton (NFT, DID, ...)
+//! coin has since been recreated further down its lineage, the spend can still be replayed
+//! against the new coin by updating the outer `Coin`'s parent info and amount and recomputing
+//! its coin id, leaving the puzzle reveal and solution untouched. Only spends whose puzzle
+//! reveal actually parses as a singleton are eligible; everything else is left alone.
+
+use chia_protocol::{Bytes32, Coin, SpendBundle};
+use chia_sdk_driver::{Puzzle, SingletonLayer, SpendContext};
+
+/// `(old_coin_id, new_parent_coin_info, new_amount)` for a singleton spend to fast-forward.
+pub type FastForwardUpdate = (Bytes32, Bytes32, u64);
+
+fn is_singleton_spend(
+    ctx: &mut SpendContext,
+    puzzle_reveal: &chia_protocol::Program,
+) -> Result<bool, String> {
+    let ptr = ctx.alloc(puzzle_reveal).map_err(|err| err.to_string())?;
+    let puzzle = Puzzle::parse(ctx.allocator(), ptr);
+    Ok(SingletonLayer::<Puzzle>::parse(ctx.allocator(), puzzle)
+        .map_err(|err| err.to_string())?
+        .is_some())
+}
+
+pub fn fast_forward_offer(
+    mut spend_bundle: SpendBundle,
+    updates: &[FastForwardUpdate],
+) -> Result<(SpendBundle, Vec<bool>), String> {
+    let mut ctx = SpendContext::new();
+    let mut eligibility = Vec::with_capacity(updates.len());
+
+    for &(old_coin_id, new_parent_coin_info, new_amount) in updates {
+        let position = spend_bundle
+            .coin_spends
+            .iter()
+            .position(|coin_spend| coin_spend.coin.coin_id() == old_coin_id);
+        let Some(position) = position else {
+            return Err(format!(
+                "no spend found for coin {old_coin_id} to fast-forward"
+            ));
+        };
+
+        if !is_singleton_spend(&mut ctx, &spend_bundle.coin_spends[position].puzzle_reveal)? {
+            eligibility.push(false);
+            continue;
+        }
+
+        let coin_spend = &mut spend_bundle.coin_spends[position];
+        coin_spend.coin = Coin::new(new_parent_coin_info, coin_spend.coin.puzzle_hash, new_amount);
+        eligibility.push(true);
+    }
+
+    Ok((spend_bundle, eligibility))
+}
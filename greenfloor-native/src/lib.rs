@@ -1,13 +1,23 @@
-use chia_protocol::{Bytes32, SpendBundle};
+mod combine;
+mod convert;
+mod fast_forward;
+mod proposal;
+mod request_uri;
+
+use chia_protocol::{Bytes32, CoinSpend, SpendBundle};
 use chia_puzzle_types::{
     Memos,
     offer::{NotarizedPayment, Payment},
 };
-use chia_sdk_driver::{AssetInfo, Offer, RequestedPayments, SpendContext, decode_offer};
+use chia_sdk_driver::{
+    AssetInfo, CatLayer, Offer, Puzzle, RequestedPayments, SingletonLayer, SpendContext,
+    decode_offer, encode_offer,
+};
 use chia_traits::Streamable;
+use convert::{bytes32_to_py, notarized_payment_to_py, offered_asset_to_py};
 use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
-use pyo3::types::PyModule;
+use pyo3::types::{PyDict, PyList, PyModule};
 
 fn to_py_value_error<E: std::fmt::Display>(err: E) -> PyErr {
     PyValueError::new_err(err.to_string())
@@ -28,6 +38,86 @@ fn validate_offer(offer: &str) -> PyResult<()> {
     Ok(())
 }
 
+fn requested_payments_to_py(
+    py: Python<'_>,
+    requested_payments: &RequestedPayments,
+) -> PyResult<Py<PyDict>> {
+    let dict = PyDict::new(py);
+
+    let xch = PyList::empty(py);
+    for notarized_payment in &requested_payments.xch {
+        xch.append(notarized_payment_to_py(py, notarized_payment)?)?;
+    }
+    dict.set_item("xch", xch)?;
+
+    let cat = PyDict::new(py);
+    for (asset_id, notarized_payments) in &requested_payments.cat {
+        let entries = PyList::empty(py);
+        for notarized_payment in notarized_payments {
+            entries.append(notarized_payment_to_py(py, notarized_payment)?)?;
+        }
+        cat.set_item(bytes32_to_py(py, *asset_id), entries)?;
+    }
+    dict.set_item("cat", cat)?;
+
+    let nft = PyDict::new(py);
+    for (launcher_id, notarized_payments) in &requested_payments.nft {
+        let entries = PyList::empty(py);
+        for notarized_payment in notarized_payments {
+            entries.append(notarized_payment_to_py(py, notarized_payment)?)?;
+        }
+        nft.set_item(bytes32_to_py(py, *launcher_id), entries)?;
+    }
+    dict.set_item("nft", nft)?;
+
+    Ok(dict.into())
+}
+
+/// `(asset_kind, asset_id, amount)` for a single offered coin: `"cat"`/`"nft"` if its puzzle
+/// reveal parses as a CAT or singleton layer (carrying the CAT's asset id or the NFT's launcher
+/// id), `"xch"` otherwise.
+fn classify_offered_coin(
+    ctx: &mut SpendContext,
+    coin_spend: &CoinSpend,
+) -> PyResult<(&'static str, Option<Bytes32>, u64)> {
+    let amount = coin_spend.coin.amount;
+    let puzzle_ptr = ctx.alloc(&coin_spend.puzzle_reveal).map_err(to_py_value_error)?;
+    let puzzle = Puzzle::parse(ctx.allocator(), puzzle_ptr);
+
+    if let Some(cat) = CatLayer::<Puzzle>::parse(ctx.allocator(), puzzle).map_err(to_py_value_error)? {
+        return Ok(("cat", Some(cat.asset_id), amount));
+    }
+    if let Some(singleton) =
+        SingletonLayer::<Puzzle>::parse(ctx.allocator(), puzzle).map_err(to_py_value_error)?
+    {
+        return Ok(("nft", Some(singleton.launcher_id), amount));
+    }
+    Ok(("xch", None, amount))
+}
+
+#[pyfunction]
+fn summarize_offer(py: Python<'_>, offer: &str) -> PyResult<Py<PyDict>> {
+    let spend_bundle = decode_offer(offer).map_err(to_py_value_error)?;
+    let mut ctx = SpendContext::new();
+    let parsed_offer = Offer::from_spend_bundle(&mut ctx, &spend_bundle).map_err(to_py_value_error)?;
+
+    let offered = PyList::empty(py);
+    for coin_spend in &parsed_offer.coin_spends {
+        let (asset_kind, asset_id, amount) = classify_offered_coin(&mut ctx, coin_spend)?;
+        offered.append(offered_asset_to_py(py, asset_kind, asset_id, amount)?)?;
+    }
+
+    let summary = PyDict::new(py);
+    summary.set_item("offered", offered)?;
+    summary.set_item(
+        "requested",
+        requested_payments_to_py(py, &parsed_offer.requested_payments)?,
+    )?;
+    summary.set_item("aggregated_signature", spend_bundle.aggregated_signature.to_bytes())?;
+
+    Ok(summary.into())
+}
+
 #[pyfunction]
 fn from_input_spend_bundle_xch(
     spend_bundle_bytes: &[u8],
@@ -60,9 +150,316 @@ fn from_input_spend_bundle_xch(
     offer_spend_bundle.to_bytes().map_err(to_py_value_error)
 }
 
+type RawNotarizedPayments = Vec<(Vec<u8>, Vec<(Vec<u8>, u64, Vec<Vec<u8>>)>)>;
+
+fn parse_memos(memos_raw: Vec<Vec<u8>>) -> PyResult<Memos> {
+    if memos_raw.is_empty() {
+        return Ok(Memos::None);
+    }
+    let memos = memos_raw
+        .into_iter()
+        .map(|memo| memo.into())
+        .collect::<Vec<_>>();
+    Ok(Memos::Some(memos))
+}
+
+fn parse_notarized_payments(
+    requested_payments: RawNotarizedPayments,
+) -> PyResult<Vec<NotarizedPayment>> {
+    let mut notarized_payments = Vec::with_capacity(requested_payments.len());
+    for (nonce_raw, payments_raw) in requested_payments {
+        let nonce = parse_bytes32(&nonce_raw, "nonce")?;
+        let mut payments = Vec::with_capacity(payments_raw.len());
+        for (puzzle_hash_raw, amount, memos_raw) in payments_raw {
+            let puzzle_hash = parse_bytes32(&puzzle_hash_raw, "puzzle_hash")?;
+            payments.push(Payment::new(puzzle_hash, amount, parse_memos(memos_raw)?));
+        }
+        notarized_payments.push(NotarizedPayment::new(nonce, payments));
+    }
+    Ok(notarized_payments)
+}
+
+#[pyfunction]
+fn from_input_spend_bundle_cat(
+    spend_bundle_bytes: &[u8],
+    requested_payments_cat: Vec<(Vec<u8>, RawNotarizedPayments)>,
+) -> PyResult<Vec<u8>> {
+    let spend_bundle = SpendBundle::from_bytes(spend_bundle_bytes).map_err(to_py_value_error)?;
+
+    let mut requested_payments = RequestedPayments::new();
+    let mut asset_info = AssetInfo::new();
+    for (asset_id_raw, notarized_payments_raw) in requested_payments_cat {
+        let asset_id = parse_bytes32(&asset_id_raw, "asset_id")?;
+        asset_info.insert_cat(asset_id);
+        requested_payments
+            .cat
+            .entry(asset_id)
+            .or_default()
+            .extend(parse_notarized_payments(notarized_payments_raw)?);
+    }
+
+    let mut ctx = SpendContext::new();
+    let offer =
+        Offer::from_input_spend_bundle(&mut ctx, spend_bundle, requested_payments, asset_info)
+            .map_err(to_py_value_error)?;
+    let offer_spend_bundle = offer.to_spend_bundle(&mut ctx).map_err(to_py_value_error)?;
+    offer_spend_bundle.to_bytes().map_err(to_py_value_error)
+}
+
+#[pyfunction]
+fn from_input_spend_bundle_nft(
+    spend_bundle_bytes: &[u8],
+    requested_payments_nft: Vec<(Vec<u8>, Vec<u8>, RawNotarizedPayments)>,
+) -> PyResult<Vec<u8>> {
+    let spend_bundle = SpendBundle::from_bytes(spend_bundle_bytes).map_err(to_py_value_error)?;
+
+    let mut requested_payments = RequestedPayments::new();
+    let mut asset_info = AssetInfo::new();
+    for (launcher_id_raw, inner_puzzle_hash_raw, notarized_payments_raw) in requested_payments_nft {
+        let launcher_id = parse_bytes32(&launcher_id_raw, "launcher_id")?;
+        let inner_puzzle_hash = parse_bytes32(&inner_puzzle_hash_raw, "inner_puzzle_hash")?;
+        asset_info.insert_nft(launcher_id, inner_puzzle_hash);
+        requested_payments
+            .nft
+            .entry(launcher_id)
+            .or_default()
+            .extend(parse_notarized_payments(notarized_payments_raw)?);
+    }
+
+    let mut ctx = SpendContext::new();
+    let offer =
+        Offer::from_input_spend_bundle(&mut ctx, spend_bundle, requested_payments, asset_info)
+            .map_err(to_py_value_error)?;
+    let offer_spend_bundle = offer.to_spend_bundle(&mut ctx).map_err(to_py_value_error)?;
+    offer_spend_bundle.to_bytes().map_err(to_py_value_error)
+}
+
+#[pyfunction]
+fn encode_request(payments: Vec<(Vec<u8>, u64, Vec<Vec<u8>>)>) -> PyResult<String> {
+    let mut parsed_payments = Vec::with_capacity(payments.len());
+    for (puzzle_hash_raw, amount, memos_raw) in payments {
+        let puzzle_hash = parse_bytes32(&puzzle_hash_raw, "puzzle_hash")?;
+        parsed_payments.push(Payment::new(puzzle_hash, amount, parse_memos(memos_raw)?));
+    }
+    Ok(request_uri::encode_request(&parsed_payments))
+}
+
+#[pyfunction]
+fn parse_request(uri: &str) -> PyResult<Vec<(Vec<u8>, u64, Vec<Vec<u8>>)>> {
+    let payments = request_uri::parse_request(uri)?;
+    Ok(payments
+        .into_iter()
+        .map(|payment| {
+            let memos = match payment.memos {
+                Memos::None => Vec::new(),
+                Memos::Some(memos) => memos.into_iter().map(|memo| memo.to_vec()).collect(),
+            };
+            (payment.puzzle_hash.to_vec(), payment.amount, memos)
+        })
+        .collect())
+}
+
+#[pyfunction]
+fn combine_offers(offers: Vec<&str>) -> PyResult<Vec<u8>> {
+    let spend_bundles = offers
+        .into_iter()
+        .map(|offer| decode_offer(offer).map_err(to_py_value_error))
+        .collect::<PyResult<Vec<_>>>()?;
+
+    let combined = combine::combine_spend_bundles(spend_bundles)?;
+
+    // Re-parse the combined bundle as an offer so a malformed merge (e.g. one that no longer
+    // balances, or whose settlement payments no longer line up) is rejected early rather than
+    // surfacing as an on-chain failure later.
+    let mut ctx = SpendContext::new();
+    Offer::from_spend_bundle(&mut ctx, &combined).map_err(to_py_value_error)?;
+
+    combined.to_bytes().map_err(to_py_value_error)
+}
+
+type RawStepOutputRef = (u32, u32, Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>);
+type RawNftRequestedPayments = Vec<(Vec<u8>, Vec<u8>, RawNotarizedPayments)>;
+
+type RawStep = (
+    Option<Vec<u8>>,
+    Option<RawStepOutputRef>,
+    RawNotarizedPayments,
+    Vec<(Vec<u8>, RawNotarizedPayments)>,
+    RawNftRequestedPayments,
+);
+
+/// Parses a step's requested payments the way `build_step` does, so both it and
+/// `step_payment_order` (the pyfunction callers use to learn `StepOutputRef::payment_index`
+/// before the step exists) merge same-asset entries identically.
+fn parse_step_requested_payments(
+    requested_payments_xch: RawNotarizedPayments,
+    requested_payments_cat: Vec<(Vec<u8>, RawNotarizedPayments)>,
+    requested_payments_nft: RawNftRequestedPayments,
+) -> PyResult<(RequestedPayments, std::collections::HashMap<Bytes32, Bytes32>)> {
+    let mut requested_payments = RequestedPayments::new();
+    requested_payments.xch = parse_notarized_payments(requested_payments_xch)?;
+    for (asset_id_raw, notarized_payments_raw) in requested_payments_cat {
+        let asset_id = parse_bytes32(&asset_id_raw, "asset_id")?;
+        requested_payments
+            .cat
+            .entry(asset_id)
+            .or_default()
+            .extend(parse_notarized_payments(notarized_payments_raw)?);
+    }
+    let mut nft_inner_puzzle_hashes = std::collections::HashMap::new();
+    for (launcher_id_raw, inner_puzzle_hash_raw, notarized_payments_raw) in requested_payments_nft {
+        let launcher_id = parse_bytes32(&launcher_id_raw, "launcher_id")?;
+        let inner_puzzle_hash = parse_bytes32(&inner_puzzle_hash_raw, "inner_puzzle_hash")?;
+        nft_inner_puzzle_hashes.insert(launcher_id, inner_puzzle_hash);
+        requested_payments
+            .nft
+            .entry(launcher_id)
+            .or_default()
+            .extend(parse_notarized_payments(notarized_payments_raw)?);
+    }
+    Ok((requested_payments, nft_inner_puzzle_hashes))
+}
+
+/// The puzzle hash/amount of every payment a step with these requested payments would expose,
+/// in the same order `StepOutputRef::payment_index` indexes into: `xch`, then each `cat`
+/// bucket, then each `nft` bucket. Callers building a multi-step proposal use this to compute
+/// the `payment_index` an earlier step's output reference should point at.
+#[pyfunction]
+fn step_payment_order(
+    requested_payments_xch: RawNotarizedPayments,
+    requested_payments_cat: Vec<(Vec<u8>, RawNotarizedPayments)>,
+    requested_payments_nft: RawNftRequestedPayments,
+) -> PyResult<Vec<(Vec<u8>, u64)>> {
+    let (requested_payments, _) = parse_step_requested_payments(
+        requested_payments_xch,
+        requested_payments_cat,
+        requested_payments_nft,
+    )?;
+    Ok(proposal::flattened_payments(&requested_payments)
+        .into_iter()
+        .map(|(puzzle_hash, amount)| (puzzle_hash.to_vec(), amount))
+        .collect())
+}
+
+fn build_step(
+    fresh_spend_bundle_bytes: Option<Vec<u8>>,
+    output_ref: Option<RawStepOutputRef>,
+    requested_payments_xch: RawNotarizedPayments,
+    requested_payments_cat: Vec<(Vec<u8>, RawNotarizedPayments)>,
+    requested_payments_nft: RawNftRequestedPayments,
+) -> PyResult<proposal::Step> {
+    let input = match (fresh_spend_bundle_bytes, output_ref) {
+        (Some(spend_bundle_bytes), None) => {
+            let spend_bundle =
+                SpendBundle::from_bytes(&spend_bundle_bytes).map_err(to_py_value_error)?;
+            proposal::StepInput::Fresh(spend_bundle)
+        }
+        (
+            None,
+            Some((
+                step_index,
+                payment_index,
+                parent_coin_info_raw,
+                puzzle_reveal_raw,
+                solution_raw,
+                signature_raw,
+            )),
+        ) => {
+            let parent_coin_info = parse_bytes32(&parent_coin_info_raw, "parent_coin_info")?;
+            let puzzle_reveal =
+                chia_protocol::Program::from_bytes(&puzzle_reveal_raw).map_err(to_py_value_error)?;
+            let solution =
+                chia_protocol::Program::from_bytes(&solution_raw).map_err(to_py_value_error)?;
+            let signature_bytes: [u8; 96] = signature_raw
+                .as_slice()
+                .try_into()
+                .map_err(|_| PyValueError::new_err("signature must be 96 bytes"))?;
+            let signature = chia_bls::Signature::from_bytes(signature_bytes)
+                .map_err(to_py_value_error)?;
+            proposal::StepInput::Output(proposal::StepOutputRef {
+                step_index: step_index as usize,
+                payment_index: payment_index as usize,
+                parent_coin_info,
+                puzzle_reveal,
+                solution,
+                signature,
+            })
+        }
+        _ => {
+            return Err(PyValueError::new_err(
+                "each step needs exactly one of a fresh spend bundle or an output reference",
+            ));
+        }
+    };
+
+    let (requested_payments, nft_inner_puzzle_hashes) = parse_step_requested_payments(
+        requested_payments_xch,
+        requested_payments_cat,
+        requested_payments_nft,
+    )?;
+
+    Ok(proposal::Step {
+        input,
+        requested_payments,
+        nft_inner_puzzle_hashes,
+    })
+}
+
+#[pyfunction]
+fn build_proposal(steps: Vec<RawStep>) -> PyResult<Vec<u8>> {
+    let steps = steps
+        .into_iter()
+        .map(|(fresh, output_ref, xch, cat, nft)| build_step(fresh, output_ref, xch, cat, nft))
+        .collect::<PyResult<Vec<_>>>()?;
+    let built = proposal::Proposal { steps };
+    built.validate().map_err(to_py_value_error)?;
+    Ok(proposal::encode_proposal(&built))
+}
+
+#[pyfunction]
+fn execute_proposal(proposal_bytes: &[u8]) -> PyResult<Vec<u8>> {
+    let proposal = proposal::decode_proposal(proposal_bytes).map_err(to_py_value_error)?;
+    let spend_bundle = proposal::execute_proposal(&proposal).map_err(to_py_value_error)?;
+    spend_bundle.to_bytes().map_err(to_py_value_error)
+}
+
+#[pyfunction]
+fn fast_forward_offer(
+    offer: &str,
+    new_parent_info: Vec<(Vec<u8>, Vec<u8>, u64)>,
+) -> PyResult<(String, Vec<bool>)> {
+    let spend_bundle = decode_offer(offer).map_err(to_py_value_error)?;
+
+    let updates = new_parent_info
+        .into_iter()
+        .map(|(old_coin_id_raw, new_parent_coin_info_raw, new_amount)| {
+            let old_coin_id = parse_bytes32(&old_coin_id_raw, "old_coin_id")?;
+            let new_parent_coin_info =
+                parse_bytes32(&new_parent_coin_info_raw, "new_parent_coin_info")?;
+            Ok((old_coin_id, new_parent_coin_info, new_amount))
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+
+    let (fast_forwarded, eligibility) = fast_forward::fast_forward_offer(spend_bundle, &updates)
+        .map_err(PyValueError::new_err)?;
+
+    let offer = encode_offer(&fast_forwarded).map_err(to_py_value_error)?;
+    Ok((offer, eligibility))
+}
+
 #[pymodule]
 fn greenfloor_native(_py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(validate_offer, m)?)?;
+    m.add_function(wrap_pyfunction!(summarize_offer, m)?)?;
     m.add_function(wrap_pyfunction!(from_input_spend_bundle_xch, m)?)?;
+    m.add_function(wrap_pyfunction!(from_input_spend_bundle_cat, m)?)?;
+    m.add_function(wrap_pyfunction!(from_input_spend_bundle_nft, m)?)?;
+    m.add_function(wrap_pyfunction!(encode_request, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_request, m)?)?;
+    m.add_function(wrap_pyfunction!(combine_offers, m)?)?;
+    m.add_function(wrap_pyfunction!(step_payment_order, m)?)?;
+    m.add_function(wrap_pyfunction!(build_proposal, m)?)?;
+    m.add_function(wrap_pyfunction!(execute_proposal, m)?)?;
+    m.add_function(wrap_pyfunction!(fast_forward_offer, m)?)?;
     Ok(())
 }
@@ -0,0 +1,540 @@
+//! A multi-step offer proposal: a plan chaining several settlement steps, where a step can
+//! consume either a freshly supplied spend bundle or a coin created by an earlier step's
+//! requested payment.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use chia_bls::Signature;
+use chia_protocol::{Bytes32, Coin, CoinSpend, Program, SpendBundle};
+use chia_puzzle_types::offer::{NotarizedPayment, Payment};
+use chia_sdk_driver::{AssetInfo, Offer, RequestedPayments, SpendContext};
+use chia_traits::Streamable;
+
+pub const PROPOSAL_SER_V1: u8 = 1;
+
+#[derive(Debug)]
+pub enum ProposalDecodingError {
+    UnsupportedVersion(u8),
+    Truncated,
+    InvalidStep(chia_traits::chia_error::Error),
+    DanglingStepOutput {
+        step_index: usize,
+        referenced_step: usize,
+    },
+    MissingStepOutput {
+        step_index: usize,
+        referenced_step: usize,
+        payment_index: usize,
+    },
+    MissingNftInnerPuzzleHash {
+        launcher_id: Bytes32,
+    },
+    InvalidSignature(String),
+}
+
+impl fmt::Display for ProposalDecodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported proposal version {version}")
+            }
+            Self::Truncated => write!(f, "proposal bytes are truncated"),
+            Self::InvalidStep(err) => write!(f, "invalid step encoding: {err}"),
+            Self::DanglingStepOutput {
+                step_index,
+                referenced_step,
+            } => write!(
+                f,
+                "step {step_index} references step {referenced_step}, which has not run yet"
+            ),
+            Self::MissingStepOutput {
+                step_index,
+                referenced_step,
+                payment_index,
+            } => write!(
+                f,
+                "step {step_index} references payment {payment_index} of step {referenced_step}, which has no such payment"
+            ),
+            Self::MissingNftInnerPuzzleHash { launcher_id } => write!(
+                f,
+                "missing inner puzzle hash for requested NFT {launcher_id}"
+            ),
+            Self::InvalidSignature(err) => write!(f, "invalid step signature: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ProposalDecodingError {}
+
+/// A coin produced by an earlier step's requested payment, identified by its position in that
+/// step's flattened payment list (`xch` payments first, then `cat` buckets, then `nft` buckets,
+/// each in insertion order).
+pub struct StepOutputRef {
+    pub step_index: usize,
+    pub payment_index: usize,
+    pub parent_coin_info: Bytes32,
+    pub puzzle_reveal: Program,
+    pub solution: Program,
+    /// Signature for this spend, e.g. the `AGG_SIG_ME` a standard wallet puzzle requires.
+    /// Identity (`Signature::default()`) is only valid when the puzzle itself needs no
+    /// signature, such as another settlement-payments spend.
+    pub signature: Signature,
+}
+
+pub enum StepInput {
+    Fresh(SpendBundle),
+    Output(StepOutputRef),
+}
+
+pub struct Step {
+    pub input: StepInput,
+    pub requested_payments: RequestedPayments,
+    /// Inner puzzle hash for each NFT launcher id this step's `requested_payments.nft`
+    /// references, needed to register `AssetInfo` the same way
+    /// `from_input_spend_bundle_nft` does.
+    pub nft_inner_puzzle_hashes: HashMap<Bytes32, Bytes32>,
+}
+
+pub struct Proposal {
+    pub steps: Vec<Step>,
+}
+
+impl Proposal {
+    pub fn validate(&self) -> Result<(), ProposalDecodingError> {
+        for (step_index, step) in self.steps.iter().enumerate() {
+            if let StepInput::Output(output_ref) = &step.input {
+                if output_ref.step_index >= step_index {
+                    return Err(ProposalDecodingError::DanglingStepOutput {
+                        step_index,
+                        referenced_step: output_ref.step_index,
+                    });
+                }
+                let payment_count =
+                    flattened_payments(&self.steps[output_ref.step_index].requested_payments).len();
+                if output_ref.payment_index >= payment_count {
+                    return Err(ProposalDecodingError::MissingStepOutput {
+                        step_index,
+                        referenced_step: output_ref.step_index,
+                        payment_index: output_ref.payment_index,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The puzzle hash/amount of every payment a step requests, in the stable order used to index
+/// `StepOutputRef::payment_index`: `xch`, then each `cat` bucket, then each `nft` bucket.
+pub fn flattened_payments(requested_payments: &RequestedPayments) -> Vec<(Bytes32, u64)> {
+    let mut flattened = Vec::new();
+    let mut collect = |notarized_payments: &[NotarizedPayment]| {
+        for notarized_payment in notarized_payments {
+            for payment in &notarized_payment.payments {
+                flattened.push((payment.puzzle_hash, payment.amount));
+            }
+        }
+    };
+    collect(&requested_payments.xch);
+    for notarized_payments in requested_payments.cat.values() {
+        collect(notarized_payments);
+    }
+    for notarized_payments in requested_payments.nft.values() {
+        collect(notarized_payments);
+    }
+    flattened
+}
+
+/// Builds the `AssetInfo` a step implies, the same way
+/// `from_input_spend_bundle_cat`/`from_input_spend_bundle_nft` register each asset id.
+pub fn asset_info_for(step: &Step) -> Result<AssetInfo, ProposalDecodingError> {
+    let mut asset_info = AssetInfo::new();
+    for asset_id in step.requested_payments.cat.keys() {
+        asset_info.insert_cat(*asset_id);
+    }
+    for launcher_id in step.requested_payments.nft.keys() {
+        let inner_puzzle_hash = step
+            .nft_inner_puzzle_hashes
+            .get(launcher_id)
+            .copied()
+            .ok_or(ProposalDecodingError::MissingNftInnerPuzzleHash {
+                launcher_id: *launcher_id,
+            })?;
+        asset_info.insert_nft(*launcher_id, inner_puzzle_hash);
+    }
+    Ok(asset_info)
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+fn write_payment(buf: &mut Vec<u8>, payment: &Payment) {
+    buf.extend_from_slice(&payment.puzzle_hash.to_bytes());
+    buf.extend_from_slice(&payment.amount.to_be_bytes());
+    match &payment.memos {
+        chia_puzzle_types::Memos::None => buf.extend_from_slice(&0u32.to_be_bytes()),
+        chia_puzzle_types::Memos::Some(memos) => {
+            buf.extend_from_slice(&(memos.len() as u32).to_be_bytes());
+            for memo in memos {
+                write_bytes(buf, memo);
+            }
+        }
+    }
+}
+
+fn write_notarized_payments(buf: &mut Vec<u8>, notarized_payments: &[NotarizedPayment]) {
+    buf.extend_from_slice(&(notarized_payments.len() as u32).to_be_bytes());
+    for notarized_payment in notarized_payments {
+        buf.extend_from_slice(&notarized_payment.nonce.to_bytes());
+        buf.extend_from_slice(&(notarized_payment.payments.len() as u32).to_be_bytes());
+        for payment in &notarized_payment.payments {
+            write_payment(buf, payment);
+        }
+    }
+}
+
+fn write_requested_payments(buf: &mut Vec<u8>, requested_payments: &RequestedPayments) {
+    write_notarized_payments(buf, &requested_payments.xch);
+    buf.extend_from_slice(&(requested_payments.cat.len() as u32).to_be_bytes());
+    for (asset_id, notarized_payments) in &requested_payments.cat {
+        buf.extend_from_slice(&asset_id.to_bytes());
+        write_notarized_payments(buf, notarized_payments);
+    }
+    buf.extend_from_slice(&(requested_payments.nft.len() as u32).to_be_bytes());
+    for (launcher_id, notarized_payments) in &requested_payments.nft {
+        buf.extend_from_slice(&launcher_id.to_bytes());
+        write_notarized_payments(buf, notarized_payments);
+    }
+}
+
+fn write_step(buf: &mut Vec<u8>, step: &Step) {
+    match &step.input {
+        StepInput::Fresh(spend_bundle) => {
+            buf.push(0);
+            write_bytes(buf, &spend_bundle.to_bytes().expect("streamable"));
+        }
+        StepInput::Output(output_ref) => {
+            buf.push(1);
+            buf.extend_from_slice(&(output_ref.step_index as u32).to_be_bytes());
+            buf.extend_from_slice(&(output_ref.payment_index as u32).to_be_bytes());
+            buf.extend_from_slice(&output_ref.parent_coin_info.to_bytes());
+            write_bytes(buf, &output_ref.puzzle_reveal.to_bytes().expect("streamable"));
+            write_bytes(buf, &output_ref.solution.to_bytes().expect("streamable"));
+            write_bytes(buf, &output_ref.signature.to_bytes());
+        }
+    }
+    write_requested_payments(buf, &step.requested_payments);
+    buf.extend_from_slice(&(step.nft_inner_puzzle_hashes.len() as u32).to_be_bytes());
+    for (launcher_id, inner_puzzle_hash) in &step.nft_inner_puzzle_hashes {
+        buf.extend_from_slice(&launcher_id.to_bytes());
+        buf.extend_from_slice(&inner_puzzle_hash.to_bytes());
+    }
+}
+
+/// Serializes a proposal as `PROPOSAL_SER_V1` followed by its steps.
+pub fn encode_proposal(proposal: &Proposal) -> Vec<u8> {
+    let mut buf = vec![PROPOSAL_SER_V1];
+    buf.extend_from_slice(&(proposal.steps.len() as u32).to_be_bytes());
+    for step in &proposal.steps {
+        write_step(&mut buf, step);
+    }
+    buf
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ProposalDecodingError> {
+        let end = self.offset + len;
+        let slice = self
+            .bytes
+            .get(self.offset..end)
+            .ok_or(ProposalDecodingError::Truncated)?;
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32, ProposalDecodingError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, ProposalDecodingError> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn bytes32(&mut self) -> Result<Bytes32, ProposalDecodingError> {
+        Ok(Bytes32::new(self.take(32)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, ProposalDecodingError> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+}
+
+fn read_payment(reader: &mut Reader<'_>) -> Result<Payment, ProposalDecodingError> {
+    let puzzle_hash = reader.bytes32()?;
+    let amount = reader.u64()?;
+    let memo_count = reader.u32()?;
+    let memos = if memo_count == 0 {
+        chia_puzzle_types::Memos::None
+    } else {
+        let mut memos = Vec::with_capacity(memo_count as usize);
+        for _ in 0..memo_count {
+            memos.push(reader.bytes()?.into());
+        }
+        chia_puzzle_types::Memos::Some(memos)
+    };
+    Ok(Payment::new(puzzle_hash, amount, memos))
+}
+
+fn read_notarized_payments(
+    reader: &mut Reader<'_>,
+) -> Result<Vec<NotarizedPayment>, ProposalDecodingError> {
+    let count = reader.u32()?;
+    let mut notarized_payments = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let nonce = reader.bytes32()?;
+        let payment_count = reader.u32()?;
+        let mut payments = Vec::with_capacity(payment_count as usize);
+        for _ in 0..payment_count {
+            payments.push(read_payment(reader)?);
+        }
+        notarized_payments.push(NotarizedPayment::new(nonce, payments));
+    }
+    Ok(notarized_payments)
+}
+
+fn read_requested_payments(
+    reader: &mut Reader<'_>,
+) -> Result<RequestedPayments, ProposalDecodingError> {
+    let mut requested_payments = RequestedPayments::new();
+    requested_payments.xch = read_notarized_payments(reader)?;
+    let cat_count = reader.u32()?;
+    for _ in 0..cat_count {
+        let asset_id = reader.bytes32()?;
+        requested_payments
+            .cat
+            .insert(asset_id, read_notarized_payments(reader)?);
+    }
+    let nft_count = reader.u32()?;
+    for _ in 0..nft_count {
+        let launcher_id = reader.bytes32()?;
+        requested_payments
+            .nft
+            .insert(launcher_id, read_notarized_payments(reader)?);
+    }
+    Ok(requested_payments)
+}
+
+fn read_step(reader: &mut Reader<'_>) -> Result<Step, ProposalDecodingError> {
+    let tag = reader.take(1)?[0];
+    let input = match tag {
+        0 => {
+            let spend_bundle_bytes = reader.bytes()?;
+            let spend_bundle = SpendBundle::from_bytes(&spend_bundle_bytes)
+                .map_err(ProposalDecodingError::InvalidStep)?;
+            StepInput::Fresh(spend_bundle)
+        }
+        1 => {
+            let step_index = reader.u32()? as usize;
+            let payment_index = reader.u32()? as usize;
+            let parent_coin_info = reader.bytes32()?;
+            let puzzle_reveal =
+                Program::from_bytes(&reader.bytes()?).map_err(ProposalDecodingError::InvalidStep)?;
+            let solution =
+                Program::from_bytes(&reader.bytes()?).map_err(ProposalDecodingError::InvalidStep)?;
+            let signature_bytes = reader.bytes()?;
+            let signature = Signature::from_bytes(
+                signature_bytes
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| ProposalDecodingError::InvalidSignature("wrong length".into()))?,
+            )
+            .map_err(|err| ProposalDecodingError::InvalidSignature(err.to_string()))?;
+            StepInput::Output(StepOutputRef {
+                step_index,
+                payment_index,
+                parent_coin_info,
+                puzzle_reveal,
+                solution,
+                signature,
+            })
+        }
+        _ => return Err(ProposalDecodingError::Truncated),
+    };
+    let requested_payments = read_requested_payments(reader)?;
+    let nft_inner_puzzle_hash_count = reader.u32()?;
+    let mut nft_inner_puzzle_hashes = HashMap::with_capacity(nft_inner_puzzle_hash_count as usize);
+    for _ in 0..nft_inner_puzzle_hash_count {
+        let launcher_id = reader.bytes32()?;
+        let inner_puzzle_hash = reader.bytes32()?;
+        nft_inner_puzzle_hashes.insert(launcher_id, inner_puzzle_hash);
+    }
+    Ok(Step {
+        input,
+        requested_payments,
+        nft_inner_puzzle_hashes,
+    })
+}
+
+pub fn decode_proposal(bytes: &[u8]) -> Result<Proposal, ProposalDecodingError> {
+    let mut reader = Reader::new(bytes);
+    let version = reader.take(1)?[0];
+    if version != PROPOSAL_SER_V1 {
+        return Err(ProposalDecodingError::UnsupportedVersion(version));
+    }
+    let step_count = reader.u32()?;
+    let mut steps = Vec::with_capacity(step_count as usize);
+    for _ in 0..step_count {
+        steps.push(read_step(&mut reader)?);
+    }
+    let proposal = Proposal { steps };
+    proposal.validate()?;
+    Ok(proposal)
+}
+
+/// Threads a single `SpendContext` through every step, turning each step's input (a fresh spend
+/// bundle, or a coin created by an earlier step's requested payment) into an `Offer`, and
+/// combines all of the resulting spends and signatures into one `SpendBundle`.
+pub fn execute_proposal(proposal: &Proposal) -> Result<SpendBundle, ProposalDecodingError> {
+    let mut ctx = SpendContext::new();
+    let mut coin_spends = Vec::new();
+    let mut signatures = Vec::new();
+    let mut step_payments: Vec<Vec<(Bytes32, u64)>> = Vec::with_capacity(proposal.steps.len());
+
+    for step in &proposal.steps {
+        let spend_bundle = match &step.input {
+            StepInput::Fresh(spend_bundle) => spend_bundle.clone(),
+            StepInput::Output(output_ref) => {
+                let (puzzle_hash, amount) = step_payments[output_ref.step_index]
+                    [output_ref.payment_index];
+                let coin = Coin::new(output_ref.parent_coin_info, puzzle_hash, amount);
+                let coin_spend = CoinSpend::new(
+                    coin,
+                    output_ref.puzzle_reveal.clone(),
+                    output_ref.solution.clone(),
+                );
+                SpendBundle::new(vec![coin_spend], output_ref.signature.clone())
+            }
+        };
+
+        let asset_info = asset_info_for(step)?;
+        let offer = Offer::from_input_spend_bundle(
+            &mut ctx,
+            spend_bundle,
+            step.requested_payments.clone(),
+            asset_info,
+        )
+        .map_err(|err| {
+            ProposalDecodingError::InvalidStep(chia_traits::chia_error::Error::custom(
+                err.to_string(),
+            ))
+        })?;
+        let offer_spend_bundle = offer.to_spend_bundle(&mut ctx).map_err(|err| {
+            ProposalDecodingError::InvalidStep(chia_traits::chia_error::Error::custom(
+                err.to_string(),
+            ))
+        })?;
+
+        step_payments.push(flattened_payments(&step.requested_payments));
+        signatures.push(offer_spend_bundle.aggregated_signature);
+        coin_spends.extend(offer_spend_bundle.coin_spends);
+    }
+
+    let aggregated_signature = chia_bls::aggregate(&signatures);
+    Ok(SpendBundle::new(coin_spends, aggregated_signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payment(byte: u8, amount: u64) -> Payment {
+        Payment::new(Bytes32::new([byte; 32]), amount, chia_puzzle_types::Memos::None)
+    }
+
+    fn fresh_step(byte: u8, amount: u64) -> Step {
+        let mut requested_payments = RequestedPayments::new();
+        requested_payments
+            .xch
+            .push(NotarizedPayment::new(Bytes32::new([byte; 32]), vec![payment(byte, amount)]));
+        Step {
+            input: StepInput::Fresh(SpendBundle::new(Vec::new(), Signature::default())),
+            requested_payments,
+            nft_inner_puzzle_hashes: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn round_trips_a_fresh_step_proposal() {
+        let proposal = Proposal {
+            steps: vec![fresh_step(1, 1_000), fresh_step(2, 2_000)],
+        };
+
+        let bytes = encode_proposal(&proposal);
+        assert_eq!(bytes[0], PROPOSAL_SER_V1);
+
+        let decoded = decode_proposal(&bytes).expect("round trip should decode");
+        assert_eq!(decoded.steps.len(), 2);
+        assert_eq!(flattened_payments(&decoded.steps[0].requested_payments), vec![(Bytes32::new([1; 32]), 1_000)]);
+        assert_eq!(flattened_payments(&decoded.steps[1].requested_payments), vec![(Bytes32::new([2; 32]), 2_000)]);
+    }
+
+    #[test]
+    fn rejects_a_step_output_referencing_itself_or_a_later_step() {
+        let mut proposal = Proposal {
+            steps: vec![fresh_step(1, 1_000)],
+        };
+        proposal.steps.push(Step {
+            input: StepInput::Output(StepOutputRef {
+                step_index: 1,
+                payment_index: 0,
+                parent_coin_info: Bytes32::new([0; 32]),
+                puzzle_reveal: Program::from_bytes(&[0x80]).unwrap(),
+                solution: Program::from_bytes(&[0x80]).unwrap(),
+                signature: Signature::default(),
+            }),
+            requested_payments: RequestedPayments::new(),
+            nft_inner_puzzle_hashes: HashMap::new(),
+        });
+
+        let err = proposal.validate().unwrap_err();
+        assert!(matches!(err, ProposalDecodingError::DanglingStepOutput { step_index: 1, referenced_step: 1 }));
+    }
+
+    #[test]
+    fn rejects_a_step_output_referencing_a_payment_that_does_not_exist() {
+        let mut proposal = Proposal {
+            steps: vec![fresh_step(1, 1_000)],
+        };
+        proposal.steps.push(Step {
+            input: StepInput::Output(StepOutputRef {
+                step_index: 0,
+                payment_index: 5,
+                parent_coin_info: Bytes32::new([0; 32]),
+                puzzle_reveal: Program::from_bytes(&[0x80]).unwrap(),
+                solution: Program::from_bytes(&[0x80]).unwrap(),
+                signature: Signature::default(),
+            }),
+            requested_payments: RequestedPayments::new(),
+            nft_inner_puzzle_hashes: HashMap::new(),
+        });
+
+        let err = proposal.validate().unwrap_err();
+        assert!(matches!(
+            err,
+            ProposalDecodingError::MissingStepOutput { step_index: 1, referenced_step: 0, payment_index: 5 }
+        ));
+    }
+}
@@ -0,0 +1,138 @@
+//! ZIP-321-style payment request URIs: a single query string enumerating payments, each indexed
+//! like ZIP-321's `address.N`/`amount.N`/`memo.N` triples. Puzzle hashes stand in for addresses
+//! since this crate never encodes bech32m addresses anywhere else.
+
+use std::collections::BTreeMap;
+
+use chia_protocol::Bytes32;
+use chia_puzzle_types::{Memos, offer::Payment};
+use pyo3::exceptions::PyValueError;
+use pyo3::PyResult;
+
+use crate::parse_bytes32;
+
+const SCHEME: &str = "chia";
+
+pub fn encode_request(payments: &[Payment]) -> String {
+    let mut parts = Vec::with_capacity(payments.len() * 3);
+    for (offset, payment) in payments.iter().enumerate() {
+        let index = offset + 1;
+        parts.push(format!(
+            "puzzle_hash.{index}={}",
+            hex::encode(*payment.puzzle_hash)
+        ));
+        parts.push(format!("amount.{index}={}", payment.amount));
+        if let Memos::Some(memos) = &payment.memos {
+            for memo in memos {
+                parts.push(format!("memo.{index}={}", hex::encode(memo)));
+            }
+        }
+    }
+    format!("{SCHEME}:?{}", parts.join("&"))
+}
+
+pub fn parse_request(uri: &str) -> PyResult<Vec<Payment>> {
+    let query = uri
+        .strip_prefix(&format!("{SCHEME}:?"))
+        .ok_or_else(|| PyValueError::new_err(format!("request URI must start with \"{SCHEME}:?\"")))?;
+
+    let mut puzzle_hashes: BTreeMap<usize, Bytes32> = BTreeMap::new();
+    let mut amounts: BTreeMap<usize, u64> = BTreeMap::new();
+    let mut memos: BTreeMap<usize, Vec<Vec<u8>>> = BTreeMap::new();
+
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| PyValueError::new_err(format!("malformed request parameter: {pair}")))?;
+        let (field, index_str) = key
+            .split_once('.')
+            .ok_or_else(|| PyValueError::new_err(format!("request parameter missing index: {key}")))?;
+        let index: usize = index_str
+            .parse()
+            .map_err(|_| PyValueError::new_err(format!("invalid request index: {index_str}")))?;
+
+        match field {
+            "puzzle_hash" => {
+                let raw =
+                    hex::decode(value).map_err(|_| PyValueError::new_err("puzzle_hash must be hex"))?;
+                let puzzle_hash = parse_bytes32(&raw, "puzzle_hash")?;
+                if puzzle_hashes.insert(index, puzzle_hash).is_some() {
+                    return Err(PyValueError::new_err(format!(
+                        "duplicate puzzle_hash.{index}"
+                    )));
+                }
+            }
+            "amount" => {
+                let amount: u64 = value.parse().map_err(|_| {
+                    PyValueError::new_err(format!("amount.{index} must be a non-negative integer"))
+                })?;
+                if amounts.insert(index, amount).is_some() {
+                    return Err(PyValueError::new_err(format!("duplicate amount.{index}")));
+                }
+            }
+            "memo" => {
+                let memo =
+                    hex::decode(value).map_err(|_| PyValueError::new_err("memo must be hex"))?;
+                memos.entry(index).or_default().push(memo);
+            }
+            _ => return Err(PyValueError::new_err(format!("unknown request field: {field}"))),
+        }
+    }
+
+    let mut payments = Vec::with_capacity(puzzle_hashes.len());
+    for (index, puzzle_hash) in puzzle_hashes {
+        let amount = amounts
+            .remove(&index)
+            .ok_or_else(|| PyValueError::new_err(format!("missing amount.{index}")))?;
+        let memos = match memos.remove(&index) {
+            Some(values) => Memos::Some(values.into_iter().map(Into::into).collect()),
+            None => Memos::None,
+        };
+        payments.push(Payment::new(puzzle_hash, amount, memos));
+    }
+    if let Some((&index, _)) = amounts.iter().next() {
+        return Err(PyValueError::new_err(format!(
+            "amount.{index} has no matching puzzle_hash.{index}"
+        )));
+    }
+
+    Ok(payments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_payments_with_and_without_memos() {
+        let payments = vec![
+            Payment::new(Bytes32::new([1; 32]), 1_000, Memos::None),
+            Payment::new(
+                Bytes32::new([2; 32]),
+                2_000,
+                Memos::Some(vec![vec![0xab, 0xcd].into()]),
+            ),
+        ];
+
+        let uri = encode_request(&payments);
+        assert!(uri.starts_with("chia:?"));
+
+        let decoded = parse_request(&uri).expect("round trip should parse");
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].puzzle_hash, payments[0].puzzle_hash);
+        assert_eq!(decoded[0].amount, payments[0].amount);
+        assert_eq!(decoded[0].memos, Memos::None);
+        assert_eq!(decoded[1].puzzle_hash, payments[1].puzzle_hash);
+        assert_eq!(decoded[1].memos, payments[1].memos);
+    }
+
+    #[test]
+    fn rejects_uri_missing_scheme() {
+        assert!(parse_request("puzzle_hash.1=00&amount.1=1").is_err());
+    }
+
+    #[test]
+    fn rejects_amount_without_matching_puzzle_hash() {
+        assert!(parse_request("chia:?amount.1=1000").is_err());
+    }
+}